@@ -1,15 +1,143 @@
 use crate::{
-    AppContext, AsyncAppContext, Context, Effect, Entity, EntityId, EventEmitter, Model, Reference,
-    Subscription, Task, WeakModel,
+    AnyModel, AppContext, AsyncAppContext, Context, Effect, Entity, EntityId, EventEmitter, Model,
+    Reference, Subscription, Task, WeakModel,
 };
 use derive_more::{Deref, DerefMut};
 use futures::FutureExt;
 use std::{
     any::{Any, TypeId},
     borrow::{Borrow, BorrowMut},
+    cell::RefCell,
+    collections::HashMap,
     future::Future,
+    ops::ControlFlow,
+    pin::Pin,
+    rc::Rc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
 };
 
+thread_local! {
+    /// The task-local values ambient to whichever task is currently being polled,
+    /// seeded by [`ModelContext::spawn_with_locals`] and restored around each poll
+    /// so they survive across await points and into any tasks spawned from within.
+    static TASK_LOCALS: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// A set of task-local values captured at spawn time by
+/// [`ModelContext::spawn_with_locals`] and made available to the spawned task, and
+/// to any nested tasks it spawns via [`ModelContext::spawn`], via
+/// [`AsyncAppContext::task_local`]. Locals don't propagate into a task spawned
+/// directly off `AsyncAppContext` itself rather than through a `ModelContext`.
+#[derive(Default, Clone)]
+pub struct TaskLocals(HashMap<TypeId, Rc<dyn Any>>);
+
+impl TaskLocals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds a value of type `V`, available to the spawned task via
+    /// [`AsyncAppContext::task_local`].
+    pub fn with<V: 'static>(mut self, value: V) -> Self {
+        self.0.insert(TypeId::of::<V>(), Rc::new(value));
+        self
+    }
+}
+
+/// Reads the task-local values ambient to the task currently being polled, if
+/// any. Used to seed child tasks spawned from within one that carries locals, so
+/// that the locals survive into tasks spawned from within without being
+/// re-specified at every `spawn` call site.
+fn current_task_locals() -> HashMap<TypeId, Rc<dyn Any>> {
+    TASK_LOCALS.with(|locals| locals.borrow().clone())
+}
+
+/// Restores a previous `TASK_LOCALS` value when dropped, including on unwind, so
+/// a panic inside a polled future can't leave another task's locals ambient on
+/// this thread.
+struct RestoreLocals(Option<HashMap<TypeId, Rc<dyn Any>>>);
+
+impl Drop for RestoreLocals {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            TASK_LOCALS.with(|locals| locals.replace(previous));
+        }
+    }
+}
+
+/// Wraps a future, swapping the ambient [`TASK_LOCALS`] for its own `locals` around
+/// each poll and restoring the previous set afterwards, so that task-local values
+/// are visible to the wrapped future without being threaded through every function
+/// signature. Any task spawned from within a poll of this future captures the
+/// locals ambient at that moment (see [`ModelContext::spawn`]), which is how
+/// locals propagate into nested spawns.
+struct WithLocals<Fut> {
+    locals: HashMap<TypeId, Rc<dyn Any>>,
+    inner: Fut,
+}
+
+impl<Fut: Future> Future for WithLocals<Fut> {
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let previous = TASK_LOCALS.with(|locals| locals.replace(this.locals.clone()));
+        let _restore = RestoreLocals(Some(previous));
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        inner.poll(cx)
+    }
+}
+
+pub struct Memo<D> {
+    value: D,
+    _source_subscriptions: Vec<Subscription>,
+}
+
+impl<D> Memo<D> {
+    pub fn get(&self) -> &D {
+        &self.value
+    }
+}
+
+#[derive(Clone)]
+pub struct Trigger<U> {
+    pub entity_id: EntityId,
+    pub handle: WeakModel<U>,
+}
+
+/// Fires `observe_created` for entity type `U`. Called from
+/// [`Context::build_model`], so only entities built that way (i.e. through a
+/// `ModelContext`, not a bare top-level `AppContext`) trigger it.
+fn fire_created_observers<U: 'static>(app: &mut AppContext, model: &Model<U>) {
+    let trigger = Trigger {
+        entity_id: model.entity_id(),
+        handle: model.downgrade(),
+    };
+    let observers = app.created_observers.clone();
+    observers.retain(&TypeId::of::<U>(), |handler| handler(&trigger, app));
+}
+
+/// Registers a one-shot release listener that fires `observe_released` for
+/// entity type `U`. Same caveat as [`fire_created_observers`]: only reachable
+/// for entities built through a `ModelContext`.
+fn watch_for_release<U: 'static>(app: &mut AppContext, model: &Model<U>) {
+    let handle = model.downgrade();
+    app.release_listeners
+        .insert(
+            model.entity_id(),
+            Box::new(move |_entity, cx| {
+                let trigger = Trigger {
+                    entity_id: handle.entity_id,
+                    handle: handle.clone(),
+                };
+                let observers = cx.released_observers.clone();
+                observers.retain(&TypeId::of::<U>(), |handler| handler(&trigger, cx));
+            }),
+        )
+        .detach();
+}
+
 #[derive(Deref, DerefMut)]
 pub struct ModelContext<'a, T> {
     #[deref]
@@ -132,6 +260,56 @@ impl<'a, T: 'static> ModelContext<'a, T> {
         )
     }
 
+    pub fn observe_created<U: 'static>(
+        &mut self,
+        mut on_created: impl FnMut(&mut T, Trigger<U>, &mut ModelContext<'_, T>) + 'static,
+    ) -> Subscription
+    where
+        T: 'static,
+    {
+        let this = self.weak_model();
+        self.app.created_observers.insert(
+            TypeId::of::<U>(),
+            Box::new(move |trigger, cx| {
+                let trigger = trigger
+                    .downcast_ref::<Trigger<U>>()
+                    .expect("invalid trigger type")
+                    .clone();
+                if let Some(this) = this.upgrade() {
+                    this.update(cx, |this, cx| on_created(this, trigger, cx));
+                    true
+                } else {
+                    false
+                }
+            }),
+        )
+    }
+
+    pub fn observe_released<U: 'static>(
+        &mut self,
+        mut on_released: impl FnMut(&mut T, Trigger<U>, &mut AppContext) + 'static,
+    ) -> Subscription
+    where
+        T: 'static,
+    {
+        let this = self.weak_model();
+        self.app.released_observers.insert(
+            TypeId::of::<U>(),
+            Box::new(move |trigger, cx| {
+                let trigger = trigger
+                    .downcast_ref::<Trigger<U>>()
+                    .expect("invalid trigger type")
+                    .clone();
+                if let Some(this) = this.upgrade() {
+                    this.update(cx, |this, cx| on_released(this, trigger, cx));
+                    true
+                } else {
+                    false
+                }
+            }),
+        )
+    }
+
     pub fn observe_global<G: 'static>(
         &mut self,
         mut f: impl FnMut(&mut T, &mut ModelContext<'_, T>) + 'static,
@@ -198,7 +376,108 @@ impl<'a, T: 'static> ModelContext<'a, T> {
         R: 'static,
     {
         let this = self.weak_model();
-        self.app.spawn(|cx| f(this, cx))
+        let locals = current_task_locals();
+        self.app.spawn(|cx| WithLocals {
+            locals,
+            inner: f(this, cx),
+        })
+    }
+
+    pub fn spawn_loop<Fut>(
+        &self,
+        mut f: impl FnMut(WeakModel<T>, AsyncAppContext) -> Fut + 'static,
+    ) -> Task<()>
+    where
+        T: 'static,
+        Fut: Future<Output = ControlFlow<(), Duration>> + 'static,
+    {
+        // Routed through `self.spawn` (rather than `self.app.spawn` directly) so
+        // the loop picks up the same `WithLocals` wrapping as every other spawn
+        // path, and sees whatever task locals are ambient when it's started.
+        self.spawn(|this, cx| async move {
+            loop {
+                if this.upgrade().is_none() {
+                    break;
+                }
+                match f(this.clone(), cx.clone()).await {
+                    ControlFlow::Continue(delay) => {
+                        if this.upgrade().is_none() {
+                            break;
+                        }
+                        cx.background_executor().timer(delay).await;
+                    }
+                    ControlFlow::Break(()) => break,
+                }
+            }
+        })
+    }
+
+    pub fn spawn_with_locals<Fut, R>(
+        &self,
+        locals: TaskLocals,
+        f: impl FnOnce(WeakModel<T>, AsyncAppContext) -> Fut,
+    ) -> Task<R>
+    where
+        T: 'static,
+        Fut: Future<Output = R> + 'static,
+        R: 'static,
+    {
+        let this = self.weak_model();
+        let mut merged = current_task_locals();
+        merged.extend(locals.0);
+        self.app.spawn(|cx| WithLocals {
+            locals: merged,
+            inner: f(this, cx),
+        })
+    }
+
+    pub fn compute<D>(
+        &mut self,
+        sources: impl IntoIterator<Item = AnyModel>,
+        compute: impl FnMut(Option<&D>, &mut ModelContext<'_, Memo<D>>) -> D + 'static,
+    ) -> Model<Memo<D>>
+    where
+        D: 'static + PartialEq,
+    {
+        let sources: Vec<AnyModel> = sources.into_iter().collect();
+        let compute = Rc::new(RefCell::new(compute));
+
+        let memo = {
+            let compute = compute.clone();
+            // Built through `self.build_model` (the `Context` override), not
+            // `self.app.build_model` directly, so `Memo<D>` entities fire
+            // `observe_created`/`observe_released` like every other entity.
+            self.build_model(|cx| {
+                let value = compute.borrow_mut()(None, cx);
+                Memo {
+                    value,
+                    _source_subscriptions: Vec::new(),
+                }
+            })
+        };
+
+        // Registered through `observe` (rather than hand-rolled against
+        // `self.app.observers`) so the subscription closures only hold a weak
+        // reference to the memo, matching every other subscription in this file;
+        // storing the resulting `Subscription`s on the memo itself ties their
+        // lifetime to it instead of leaking them for the life of the process.
+        memo.update(self, |memo, cx| {
+            memo._source_subscriptions = sources
+                .iter()
+                .map(|source| {
+                    let compute = compute.clone();
+                    cx.observe(source, move |memo, _source, cx| {
+                        let value = compute.borrow_mut()(Some(&memo.value), cx);
+                        if value != memo.value {
+                            memo.value = value;
+                            cx.notify();
+                        }
+                    })
+                })
+                .collect();
+        });
+
+        memo
     }
 }
 
@@ -222,7 +501,10 @@ impl<'a, T> Context for ModelContext<'a, T> {
         &mut self,
         build_model: impl FnOnce(&mut Self::ModelContext<'_, U>) -> U,
     ) -> Model<U> {
-        self.app.build_model(build_model)
+        let model = self.app.build_model(build_model);
+        fire_created_observers(&mut self.app, &model);
+        watch_for_release(&mut self.app, &model);
+        model
     }
 
     fn update_model<U: 'static, R>(
@@ -244,4 +526,201 @@ impl<T> BorrowMut<AppContext> for ModelContext<'_, T> {
     fn borrow_mut(&mut self) -> &mut AppContext {
         &mut self.app
     }
-}
\ No newline at end of file
+}
+
+impl AsyncAppContext {
+    /// Reads a task-local value of type `V` seeded by an enclosing
+    /// [`ModelContext::spawn_with_locals`] call, if one is ambient to the task
+    /// currently being polled.
+    pub fn task_local<V: 'static>(&self) -> Option<Rc<V>> {
+        TASK_LOCALS.with(|locals| {
+            locals
+                .borrow()
+                .get(&TypeId::of::<V>())
+                .cloned()
+                .map(|value| {
+                    value
+                        .downcast::<V>()
+                        .ok()
+                        .expect("invalid task-local type")
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestAppContext;
+    use std::{
+        future::poll_fn,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[gpui::test]
+    fn test_compute_suppresses_notify_when_value_unchanged(cx: &mut TestAppContext) {
+        let source = cx.build_model(|_| 1_i32);
+        let recomputes = Arc::new(AtomicUsize::new(0));
+
+        // Hosted on a separate model so the eager first computation can read
+        // `source` without it being mid-update.
+        let host = cx.build_model(|_| ());
+        let memo = host.update(cx, |_, cx| {
+            let recomputes = recomputes.clone();
+            let source_handle = source.clone();
+            cx.compute([source.clone().into()], move |_, cx| {
+                recomputes.fetch_add(1, Ordering::SeqCst);
+                (*source_handle.read(cx)).clamp(0, 10)
+            })
+        });
+
+        let notifications = Arc::new(AtomicUsize::new(0));
+        let _subscription = cx.update(|cx| {
+            let notifications = notifications.clone();
+            cx.observe(&memo, move |_, _, _| {
+                notifications.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+
+        // Source notifies, but the clamped value is unchanged (1 stays in 0..=10):
+        // the memo must recompute without notifying its own observers.
+        source.update(cx, |_, cx| cx.notify());
+        assert_eq!(notifications.load(Ordering::SeqCst), 0);
+
+        // Source changes to a value that changes the clamped output.
+        source.update(cx, |value, cx| {
+            *value = 20;
+            cx.notify();
+        });
+        assert_eq!(notifications.load(Ordering::SeqCst), 1);
+    }
+
+    #[gpui::test]
+    fn test_observe_created_and_released_fire_for_every_entity(cx: &mut TestAppContext) {
+        struct Tracked;
+
+        let created = Arc::new(AtomicUsize::new(0));
+        let released = Arc::new(AtomicUsize::new(0));
+        let root = cx.build_model(|_| ());
+
+        let _created_subscription = root.update(cx, |_, cx| {
+            let created = created.clone();
+            cx.observe_created::<Tracked>(move |_, _, _| {
+                created.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+        let _released_subscription = root.update(cx, |_, cx| {
+            let released = released.clone();
+            cx.observe_released::<Tracked>(move |_, _, _| {
+                released.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+
+        // Built from inside `root`'s update, so this goes through
+        // `ModelContext::build_model` (which fires the lifecycle observers) rather
+        // than a bare top-level `TestAppContext::build_model`, which doesn't fire
+        // them.
+        let tracked = root.update(cx, |_, cx| cx.build_model(|_| Tracked));
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+        assert_eq!(released.load(Ordering::SeqCst), 0);
+
+        drop(tracked);
+        cx.update(|cx| cx.drop_dead_entities());
+        assert_eq!(released.load(Ordering::SeqCst), 1);
+    }
+
+    #[gpui::test]
+    async fn test_spawn_loop_stops_when_model_is_dropped(cx: &mut TestAppContext) {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let model = cx.build_model(|_| ());
+
+        let task = model.update(cx, |_, cx| {
+            let ticks = ticks.clone();
+            cx.spawn_loop(move |_, _| {
+                let ticks = ticks.clone();
+                async move {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    ControlFlow::Continue(Duration::from_millis(0))
+                }
+            })
+        });
+
+        cx.executor().run_until_parked();
+        let ticks_before_drop = ticks.load(Ordering::SeqCst);
+        assert!(ticks_before_drop > 0);
+
+        drop(model);
+        cx.update(|cx| cx.drop_dead_entities());
+        cx.executor().run_until_parked();
+
+        // No further ticks once the model the loop was bound to is gone.
+        assert_eq!(ticks.load(Ordering::SeqCst), ticks_before_drop);
+        drop(task);
+    }
+
+    #[test]
+    fn test_with_locals_restores_on_panic() {
+        struct RequestId(u32);
+
+        let locals = TaskLocals::new().with(RequestId(42));
+        let mut future = Box::pin(WithLocals {
+            locals: locals.0,
+            inner: async {
+                panic!("boom");
+            },
+        });
+
+        let poll_once = poll_fn(|cx| {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                future.as_mut().poll(cx)
+            }));
+            Poll::Ready(result)
+        });
+        let result = futures::executor::block_on(poll_once);
+
+        assert!(result.is_err());
+        // The panic unwound through `WithLocals::poll`, but its `RestoreLocals`
+        // guard must still have run, leaving no locals ambient on this thread.
+        TASK_LOCALS.with(|locals| assert!(locals.borrow().is_empty()));
+    }
+
+    #[gpui::test]
+    async fn test_spawn_with_locals_propagates_into_nested_model_context_spawn(
+        cx: &mut TestAppContext,
+    ) {
+        struct RequestId(u32);
+
+        let model = cx.build_model(|_| ());
+        let seen = Arc::new(AtomicUsize::new(0));
+
+        let task = model.update(cx, |_, cx| {
+            let seen = seen.clone();
+            cx.spawn_with_locals(
+                TaskLocals::new().with(RequestId(7)),
+                move |this, mut cx| async move {
+                    // Nest another spawn through `ModelContext::spawn` (not
+                    // `AsyncAppContext::spawn` directly) from inside the outer
+                    // task; it should still see the locals seeded above.
+                    let nested = this
+                        .update(&mut cx, |_, cx| {
+                            let seen = seen.clone();
+                            cx.spawn(|_, cx| async move {
+                                if let Some(id) = cx.task_local::<RequestId>() {
+                                    seen.store(id.0 as usize, Ordering::SeqCst);
+                                }
+                            })
+                        })
+                        .unwrap();
+                    nested.await;
+                },
+            )
+        });
+
+        cx.executor().run_until_parked();
+        assert_eq!(seen.load(Ordering::SeqCst), 7);
+        drop(task);
+    }
+}